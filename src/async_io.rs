@@ -0,0 +1,236 @@
+//! Generic async wrapper for arbitrary mio/`AsRawFd` event sources.
+//!
+//! `Async<T>` registers any `mio::event::Source` with the reactor once and
+//! exposes `readable()`/`writable()` futures that resolve when the OS
+//! reports the source ready, plus `read_with`/`write_with` helpers that
+//! retry a closure until it stops returning `WouldBlock`. Protocol wrappers
+//! (`FastSocket`, `FastUdpSocket`, ...) are thin builders on top of this.
+//!
+//! Every one of these takes `&self`: registration happens once, up front,
+//! while construction still has unique access to the source, so nothing
+//! past `new` needs `&mut Async<T>`. That's what lets a socket be wrapped
+//! in an `Arc` and handed to two tasks at once — one awaiting `readable()`
+//! while another awaits `writable()` on the same source.
+
+use std::{
+    future::Future,
+    io::{self, ErrorKind},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use mio::{event::Source, Interest, Token};
+
+use crate::reactor::Reactor;
+
+/// Wraps a non-blocking `mio` source and drives its readiness through the reactor.
+pub struct Async<T: Source> {
+    source: T,
+    token: Token,
+    reactor: Arc<Reactor>,
+}
+
+impl<T: Source + Unpin> Async<T> {
+    /// Registers `source` with `reactor` and wraps it.
+    ///
+    /// Both interests are registered up front so the same token serves both
+    /// `readable()` and `writable()`.
+    pub fn new(mut source: T, reactor: Arc<Reactor>) -> io::Result<Self> {
+        let token = reactor.register_source();
+        reactor
+            .poller()
+            .registry()
+            .register(&mut source, token, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self {
+            source,
+            token,
+            reactor,
+        })
+    }
+
+    /// Borrows the wrapped source.
+    #[inline(always)]
+    pub fn get_ref(&self) -> &T {
+        &self.source
+    }
+
+    /// Mutably borrows the wrapped source.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.source
+    }
+
+    /// Access the reactor this source is registered with.
+    #[inline(always)]
+    pub fn reactor(&self) -> &Arc<Reactor> {
+        &self.reactor
+    }
+
+    /// Returns a future that resolves once the source is readable.
+    pub fn readable(&self) -> Readable<'_, T> {
+        Readable { io: self }
+    }
+
+    /// Returns a future that resolves once the source is writable.
+    pub fn writable(&self) -> Writable<'_, T> {
+        Writable { io: self }
+    }
+
+    /// Retries `op` until it succeeds or fails with an error other than `WouldBlock`,
+    /// awaiting readability between attempts.
+    pub async fn read_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.source) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => self.readable().await?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Retries `op` until it succeeds or fails with an error other than `WouldBlock`,
+    /// awaiting writability between attempts.
+    pub async fn write_with<R>(&self, mut op: impl FnMut(&T) -> io::Result<R>) -> io::Result<R> {
+        loop {
+            match op(&self.source) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => self.writable().await?,
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<T: Source> Drop for Async<T> {
+    fn drop(&mut self) {
+        let _ = self.reactor.poller().registry().deregister(&mut self.source);
+        self.reactor.deregister(self.token);
+    }
+}
+
+/// Future returned by [`Async::readable`].
+pub struct Readable<'a, T: Source + Unpin> {
+    io: &'a Async<T>,
+}
+
+impl<T: Source + Unpin> Future for Readable<'_, T> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let token = this.io.token;
+        // Re-check actual readiness on every poll rather than trusting that a
+        // re-poll means this direction fired: the waker may be shared with
+        // other pending sub-futures (e.g. a `select!` against a `Timer`).
+        if this.io.reactor.take_reader_ready(token) {
+            return Poll::Ready(Ok(()));
+        }
+        this.io.reactor.set_reader(token, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// Future returned by [`Async::writable`].
+pub struct Writable<'a, T: Source + Unpin> {
+    io: &'a Async<T>,
+}
+
+impl<T: Source + Unpin> Future for Writable<'_, T> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let token = this.io.token;
+        // Re-check actual readiness on every poll; see `Readable::poll`.
+        if this.io.reactor.take_writer_ready(token) {
+            return Poll::Ready(Ok(()));
+        }
+        this.io.reactor.set_writer(token, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener as StdTcpListener,
+        task::Wake,
+    };
+
+    use mio::net::TcpStream as MioTcpStream;
+
+    use crate::poller::Poller;
+
+    struct NoopWake;
+
+    impl Wake for NoopWake {
+        fn wake(self: Arc<Self>) {}
+        fn wake_by_ref(self: &Arc<Self>) {}
+    }
+
+    fn noop_waker() -> std::task::Waker {
+        std::task::Waker::from(Arc::new(NoopWake))
+    }
+
+    /// A connected loopback pair, each half wrapped and registered with `reactor`.
+    fn connected_pair(reactor: &Arc<Reactor>) -> (Async<MioTcpStream>, Async<MioTcpStream>) {
+        let listener = StdTcpListener::bind("127.0.0.1:0").unwrap();
+        let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        client.set_nonblocking(true).unwrap();
+        server.set_nonblocking(true).unwrap();
+        (
+            Async::new(MioTcpStream::from_std(client), reactor.clone()).unwrap(),
+            Async::new(MioTcpStream::from_std(server), reactor.clone()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn read_with_retries_past_would_block_until_the_write_arrives() {
+        let reactor = Reactor::new(Poller::new().unwrap()).unwrap();
+        let (client, server) = connected_pair(&reactor);
+
+        let mut buf = [0u8; 2];
+        let mut read_fut = Box::pin(async { server.read_with(|mut s| s.read(&mut buf)).await });
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(
+            read_fut.as_mut().poll(&mut cx).is_pending(),
+            "no data has been written yet, so the first poll must retry on WouldBlock"
+        );
+
+        let mut c = client.get_ref();
+        c.write_all(b"hi").unwrap();
+        reactor.poll_events(Some(1_000)).unwrap();
+
+        match read_fut.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(n)) => assert_eq!(n, 2),
+            other => panic!("expected the retried read to complete, got {other:?}"),
+        }
+        drop(read_fut);
+        assert_eq!(&buf, b"hi");
+    }
+
+    #[test]
+    fn write_with_delivers_data_the_peer_can_read_back() {
+        let reactor = Reactor::new(Poller::new().unwrap()).unwrap();
+        let (client, server) = connected_pair(&reactor);
+
+        let mut write_fut = Box::pin(async { client.write_with(|mut s| s.write(b"ok")).await });
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let n = match write_fut.as_mut().poll(&mut cx) {
+            Poll::Ready(Ok(n)) => n,
+            other => panic!("a fresh socket's write side should be immediately writable, got {other:?}"),
+        };
+        assert_eq!(n, 2);
+
+        let mut buf = [0u8; 2];
+        let mut s = server.get_ref();
+        s.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ok");
+    }
+}
@@ -0,0 +1,233 @@
+//! Multi-threaded work-stealing executor, modeled on smol's `Global`/`Worker`
+//! split: a global run queue plus one sharded local queue per worker thread,
+//! with idle workers parked until new work is notified.
+
+use std::{cell::RefCell, sync::Arc};
+
+use concurrent_queue::ConcurrentQueue;
+use parking_lot::{Condvar, Mutex, RwLock};
+use rand::Rng;
+use slab::Slab;
+
+use crate::task::Task;
+
+/// A worker thread's own shard: its slab id plus the queue handle itself.
+#[derive(Clone)]
+struct LocalShard {
+    id: usize,
+    queue: Arc<ConcurrentQueue<Arc<Task>>>,
+}
+
+thread_local! {
+    /// The local shard of the worker currently running on this thread, if any.
+    /// Lets a task rescheduling itself from inside a worker land back on the
+    /// same shard instead of the contended global queue, for cache locality.
+    static CURRENT_SHARD: RefCell<Option<LocalShard>> = const { RefCell::new(None) };
+}
+
+/// Blocks a worker thread until `unpark` is called.
+struct Parker {
+    woken: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Parker {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            woken: Mutex::new(false),
+            condvar: Condvar::new(),
+        })
+    }
+
+    fn park(&self) {
+        let mut woken = self.woken.lock();
+        while !*woken {
+            self.condvar.wait(&mut woken);
+        }
+        *woken = false;
+    }
+
+    fn unpark(&self) {
+        *self.woken.lock() = true;
+        self.condvar.notify_one();
+    }
+}
+
+/// Global run queue, per-worker shards, and the sleeper list workers park on.
+pub struct Shards {
+    global: ConcurrentQueue<Arc<Task>>,
+    locals: RwLock<Slab<Arc<ConcurrentQueue<Arc<Task>>>>>,
+    sleepers: Mutex<Vec<Arc<Parker>>>,
+}
+
+impl Shards {
+    fn new() -> Arc<Self> {
+        Arc::new(Self {
+            global: ConcurrentQueue::unbounded(),
+            locals: RwLock::new(Slab::new()),
+            sleepers: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Schedules a task, then wakes one sleeping worker, if any.
+    ///
+    /// If called from inside a worker thread (a task rescheduling itself via
+    /// its waker), the task goes onto that worker's own local shard. Any
+    /// other caller (e.g. the thread driving the reactor) pushes onto the
+    /// global queue instead, so idle workers can pick it up or steal it.
+    pub fn spawn_task(&self, task: Arc<Task>) {
+        let local = CURRENT_SHARD.with(|shard| shard.borrow().clone());
+        match local {
+            Some(LocalShard { queue, .. }) => {
+                let _ = queue.push(task);
+            }
+            None => {
+                let _ = self.global.push(task);
+            }
+        }
+        self.notify();
+    }
+
+    /// Wakes one sleeping worker, if any are parked.
+    ///
+    /// Pops the sleeper under the same lock `worker_loop` uses to register
+    /// itself before parking, rather than gating on a separate "already
+    /// notified" flag. A flag that only clears when some worker goes idle
+    /// again stays stuck at "notified" for as long as that worker is busy,
+    /// silently swallowing every other spawn_task/notify in the meantime —
+    /// exactly the bug this used to have.
+    fn notify(&self) {
+        if let Some(parker) = self.sleepers.lock().pop() {
+            parker.unpark();
+        }
+    }
+
+    /// Registers a new local shard for a worker, returning its id and queue.
+    fn register_local(&self) -> (usize, Arc<ConcurrentQueue<Arc<Task>>>) {
+        let queue = Arc::new(ConcurrentQueue::unbounded());
+        let id = self.locals.write().insert(queue.clone());
+        (id, queue)
+    }
+
+    /// Attempts to steal a task from a random shard other than `skip`.
+    fn steal(&self, skip: usize) -> Option<Arc<Task>> {
+        let locals = self.locals.read();
+        if locals.len() <= 1 {
+            return None;
+        }
+        let start = rand::thread_rng().gen_range(0..locals.len());
+        locals
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(locals.len())
+            .filter(|(id, _)| *id != skip)
+            .find_map(|(_, queue)| queue.pop().ok())
+    }
+
+    /// Spawns `num_workers` OS threads draining this queue set, returning the
+    /// handle used to route `Reactor::spawn_task` onto it.
+    pub fn spawn_workers(num_workers: usize) -> Arc<Self> {
+        let shards = Self::new();
+        for _ in 0..num_workers {
+            let shards = shards.clone();
+            let (id, local) = shards.register_local();
+            std::thread::spawn(move || worker_loop(shards, id, local));
+        }
+        shards
+    }
+}
+
+/// Drains `local` first, then the global queue, then steals from a sibling
+/// shard; parks when all three come up empty.
+fn worker_loop(shards: Arc<Shards>, id: usize, local: Arc<ConcurrentQueue<Arc<Task>>>) {
+    CURRENT_SHARD.with(|shard| {
+        *shard.borrow_mut() = Some(LocalShard {
+            id,
+            queue: local.clone(),
+        })
+    });
+
+    let parker = Parker::new();
+    loop {
+        if let Ok(task) = local.pop() {
+            task.poll();
+            continue;
+        }
+        if let Ok(task) = shards.global.pop() {
+            task.poll();
+            continue;
+        }
+        if let Some(task) = shards.steal(id) {
+            task.poll();
+            continue;
+        }
+
+        // Register as a sleeper and re-check for work under the same lock
+        // `notify` pops from, so a task that lands between the emptiness
+        // checks above and here is guaranteed to see this parker in the
+        // list (and `notify` guaranteed to find it) rather than racing a
+        // notification that arrives just before we'd otherwise park.
+        let mut sleepers = shards.sleepers.lock();
+        if local.is_empty() && shards.global.is_empty() {
+            sleepers.push(parker.clone());
+            drop(sleepers);
+            parker.park();
+        }
+        // Otherwise work showed up since the last check: loop back around
+        // and drain it instead of parking. Don't register as a sleeper —
+        // there'd be nothing left to pop that entry back out.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactor::Reactor;
+
+    fn dummy_task() -> Arc<Task> {
+        let reactor = Reactor::new(crate::poller::Poller::new().unwrap()).unwrap();
+        Arc::new(Task::new(Box::pin(std::future::pending::<()>()), reactor))
+    }
+
+    #[test]
+    fn steal_is_none_with_no_shards_registered() {
+        let shards = Shards::new();
+        assert!(shards.steal(0).is_none());
+    }
+
+    #[test]
+    fn steal_is_none_with_a_single_shard() {
+        let shards = Shards::new();
+        let (id, queue) = shards.register_local();
+        let _ = queue.push(dummy_task());
+        // The only shard is the one being skipped, so there's nothing else
+        // to steal from even though it's non-empty.
+        assert!(shards.steal(id).is_none());
+    }
+
+    #[test]
+    fn steal_finds_a_task_on_a_sibling_shard_but_not_its_own() {
+        let shards = Shards::new();
+        let (id_a, queue_a) = shards.register_local();
+        let (id_b, _queue_b) = shards.register_local();
+        let task = dummy_task();
+        let _ = queue_a.push(task.clone());
+
+        assert!(shards.steal(id_a).is_none(), "a shard can't steal from itself");
+        let stolen = shards.steal(id_b).expect("should steal from the sibling shard");
+        assert!(Arc::ptr_eq(&stolen, &task));
+        assert!(shards.steal(id_b).is_none(), "the task was already stolen");
+    }
+
+    #[test]
+    fn spawn_task_with_no_local_shard_lands_on_the_global_queue() {
+        let shards = Shards::new();
+        let task = dummy_task();
+
+        shards.spawn_task(task.clone());
+
+        let popped = shards.global.pop().expect("spawn_task should push onto the global queue");
+        assert!(Arc::ptr_eq(&popped, &task));
+    }
+}
@@ -5,94 +5,134 @@ use std::{
     io::{self, Read, Write},
     net::SocketAddr,
     sync::Arc,
-    task::Waker,
 };
 
-use mio::{
-    event::Source,
-    net::{TcpListener, TcpStream},
-    Interest, Registry, Token,
-};
+use mio::net::{TcpListener, TcpStream, UdpSocket};
 
+use crate::async_io::Async;
 use crate::reactor::Reactor;
 
 /// Wrapper around a non-blocking TCP stream.
+///
+/// Cheap to clone (an `Arc` around the shared `Async<TcpStream>`), so one
+/// task can read while another writes the same connection; see [`split`](FastSocket::split)
+/// for dedicated read/write handles.
+#[derive(Clone)]
 pub struct FastSocket {
-    stream: TcpStream,
-    token: Option<Token>,
-    reactor: Arc<Reactor>,
+    io: Arc<Async<TcpStream>>,
 }
 
 impl FastSocket {
     /// Connects to a remote address using non-blocking socket.
     #[inline(always)]
     pub fn connect(addr: SocketAddr, reactor: Arc<Reactor>) -> io::Result<Self> {
-        let mut stream = TcpStream::connect(addr)?;
-        stream.set_nonblocking(true)?;
+        let stream = TcpStream::connect(addr)?;
         Ok(Self {
-            stream,
-            token: None,
-            reactor,
+            io: Arc::new(Async::new(stream, reactor)?),
         })
     }
 
-    /// Registers the socket with the reactor using EDGE-TRIGGERED mode.
-    pub fn register(&mut self, interest: Interest, waker: Waker) -> io::Result<()> {
-        let token = self.reactor.register_waker(waker);
-        self.reactor
-            .poller()
-            .registry()
-            .register(&mut self.stream, token, interest)?;
-        self.token = Some(token);
-        Ok(())
+    /// Resolves once the socket is readable.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.io.readable().await
+    }
+
+    /// Resolves once the socket is writable.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.io.writable().await
     }
 
-    /// Reregisters the socket to change interest.
+    /// Attempts to read into the provided buffer. Use in a loop until WouldBlock.
     #[inline(always)]
-    pub fn reregister(&mut self, interest: Interest) -> io::Result<()> {
-        if let Some(token) = self.token {
-            self.reactor
-                .poller()
-                .registry()
-                .reregister(&mut self.stream, token, interest)?;
-        }
-        Ok(())
+    pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut stream = self.io.get_ref();
+        stream.read(buf)
     }
 
-    /// Deregisters this socket from the reactor.
+    /// Attempts to write buffer to stream. Use in a loop until WouldBlock.
     #[inline(always)]
-    pub fn deregister(&mut self) -> io::Result<()> {
-        self.reactor.poller().registry().deregister(&mut self.stream)?;
-        if let Some(token) = self.token.take() {
-            self.reactor.deregister(token);
-        }
-        Ok(())
+    pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut stream = self.io.get_ref();
+        stream.write(buf)
+    }
+
+    /// Reads into `buf`, awaiting readability between `WouldBlock` attempts.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.read_with(|mut stream| stream.read(buf)).await
+    }
+
+    /// Writes `buf`, awaiting writability between `WouldBlock` attempts.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write_with(|mut stream| stream.write(buf)).await
+    }
+
+    /// Splits the socket into independent read/write halves sharing the same
+    /// underlying connection, so one task can own each direction.
+    pub fn split(&self) -> (FastSocketReadHalf, FastSocketWriteHalf) {
+        (
+            FastSocketReadHalf { io: self.io.clone() },
+            FastSocketWriteHalf { io: self.io.clone() },
+        )
+    }
+
+    /// Access raw mio stream.
+    #[inline(always)]
+    pub fn raw(&self) -> &TcpStream {
+        self.io.get_ref()
+    }
+}
+
+/// Read half of a [`FastSocket`] produced by [`FastSocket::split`].
+pub struct FastSocketReadHalf {
+    io: Arc<Async<TcpStream>>,
+}
+
+impl FastSocketReadHalf {
+    /// Resolves once the socket is readable.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.io.readable().await
     }
 
     /// Attempts to read into the provided buffer. Use in a loop until WouldBlock.
     #[inline(always)]
-    pub fn try_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.stream.read(buf)
+    pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut stream = self.io.get_ref();
+        stream.read(buf)
+    }
+
+    /// Reads into `buf`, awaiting readability between `WouldBlock` attempts.
+    pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.read_with(|mut stream| stream.read(buf)).await
+    }
+}
+
+/// Write half of a [`FastSocket`] produced by [`FastSocket::split`].
+pub struct FastSocketWriteHalf {
+    io: Arc<Async<TcpStream>>,
+}
+
+impl FastSocketWriteHalf {
+    /// Resolves once the socket is writable.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.io.writable().await
     }
 
     /// Attempts to write buffer to stream. Use in a loop until WouldBlock.
     #[inline(always)]
-    pub fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.stream.write(buf)
+    pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut stream = self.io.get_ref();
+        stream.write(buf)
     }
 
-    /// Access raw mio stream.
-    #[inline(always)]
-    pub fn raw(&self) -> &TcpStream {
-        &self.stream
+    /// Writes `buf`, awaiting writability between `WouldBlock` attempts.
+    pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write_with(|mut stream| stream.write(buf)).await
     }
 }
 
 /// Wrapper around a non-blocking TCP listener (server).
 pub struct FastListener {
-    listener: TcpListener,
-    token: Option<Token>,
-    reactor: Arc<Reactor>,
+    io: Async<TcpListener>,
 }
 
 impl FastListener {
@@ -100,45 +140,31 @@ impl FastListener {
     #[inline(always)]
     pub fn bind(addr: SocketAddr, reactor: Arc<Reactor>) -> io::Result<Self> {
         let listener = TcpListener::bind(addr)?;
-        listener.set_nonblocking(true)?;
         Ok(Self {
-            listener,
-            token: None,
-            reactor,
+            io: Async::new(listener, reactor)?,
         })
     }
 
-    /// Registers with reactor using edge-triggered read interest.
-    pub fn register(&mut self, waker: Waker) -> io::Result<()> {
-        let token = self.reactor.register_waker(waker);
-        self.reactor
-            .poller()
-            .registry()
-            .register(&mut self.listener, token, Interest::READABLE)?;
-        self.token = Some(token);
-        Ok(())
-    }
-
-    /// Deregisters the listener.
+    /// Accepts one pending connection. Use in a loop until WouldBlock.
     #[inline(always)]
-    pub fn deregister(&mut self) -> io::Result<()> {
-        self.reactor.poller().registry().deregister(&mut self.listener)?;
-        if let Some(token) = self.token.take() {
-            self.reactor.deregister(token);
-        }
-        Ok(())
+    pub fn try_accept(&self) -> io::Result<(FastSocket, SocketAddr)> {
+        let reactor = self.io.reactor().clone();
+        let (stream, addr) = self.io.get_ref().accept()?;
+        Ok((
+            FastSocket {
+                io: Arc::new(Async::new(stream, reactor)?),
+            },
+            addr,
+        ))
     }
 
-    /// Accepts as many connections as available (use in loop).
-    #[inline(always)]
-    pub fn try_accept(&mut self) -> io::Result<(FastSocket, SocketAddr)> {
-        let (mut stream, addr) = self.listener.accept()?;
-        stream.set_nonblocking(true)?;
+    /// Accepts the next connection, awaiting readability between `WouldBlock` attempts.
+    pub async fn accept(&self) -> io::Result<(FastSocket, SocketAddr)> {
+        let reactor = self.io.reactor().clone();
+        let (stream, addr) = self.io.read_with(|listener| listener.accept()).await?;
         Ok((
             FastSocket {
-                stream,
-                token: None,
-                reactor: self.reactor.clone(),
+                io: Arc::new(Async::new(stream, reactor)?),
             },
             addr,
         ))
@@ -147,6 +173,248 @@ impl FastListener {
     /// Access raw mio listener.
     #[inline(always)]
     pub fn raw(&self) -> &TcpListener {
-        &self.listener
+        self.io.get_ref()
+    }
+}
+
+/// Wrapper around a non-blocking UDP socket.
+///
+/// Cheap to clone (an `Arc` around the shared `Async<UdpSocket>`), so one
+/// task can send while another receives on the same socket.
+#[derive(Clone)]
+pub struct FastUdpSocket {
+    io: Arc<Async<UdpSocket>>,
+}
+
+impl FastUdpSocket {
+    /// Binds to a local address using non-blocking mode.
+    #[inline(always)]
+    pub fn bind(addr: SocketAddr, reactor: Arc<Reactor>) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Self {
+            io: Arc::new(Async::new(socket, reactor)?),
+        })
+    }
+
+    /// Attempts to send `buf` to `target`. Use in a loop until WouldBlock.
+    #[inline(always)]
+    pub fn try_send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.io.get_ref().send_to(buf, target)
+    }
+
+    /// Attempts to receive a datagram into `buf`. Use in a loop until WouldBlock.
+    #[inline(always)]
+    pub fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.io.get_ref().recv_from(buf)
+    }
+
+    /// Attempts to send `buf` to the connected peer. Use in a loop until WouldBlock.
+    #[inline(always)]
+    pub fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.get_ref().send(buf)
+    }
+
+    /// Attempts to receive from the connected peer. Use in a loop until WouldBlock.
+    #[inline(always)]
+    pub fn try_recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.get_ref().recv(buf)
+    }
+
+    /// Sends `buf` to `target`, awaiting writability between `WouldBlock` attempts.
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        self.io.write_with(|socket| socket.send_to(buf, target)).await
+    }
+
+    /// Receives a datagram into `buf`, awaiting readability between `WouldBlock` attempts.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.io.read_with(|socket| socket.recv_from(buf)).await
+    }
+
+    /// Sends `buf` to the connected peer. Requires a prior call to `connect`.
+    pub async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write_with(|socket| socket.send(buf)).await
+    }
+
+    /// Receives from the connected peer. Requires a prior call to `connect`.
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.io.read_with(|socket| socket.recv(buf)).await
+    }
+
+    /// Connects the socket to a remote address, restricting `send`/`recv` to that peer.
+    #[inline(always)]
+    pub fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        self.io.get_ref().connect(addr)
+    }
+
+    /// Access raw mio socket.
+    #[inline(always)]
+    pub fn raw(&self) -> &UdpSocket {
+        self.io.get_ref()
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        io::{self, Read, Write},
+        path::Path,
+        sync::Arc,
+    };
+
+    use mio::net::{UnixListener, UnixStream};
+
+    use crate::async_io::Async;
+    use crate::reactor::Reactor;
+
+    /// Wrapper around a non-blocking Unix-domain stream.
+    ///
+    /// Cheap to clone (an `Arc` around the shared `Async<UnixStream>`), so
+    /// one task can read while another writes the same connection; see
+    /// [`split`](FastUnixStream::split) for dedicated read/write handles.
+    #[derive(Clone)]
+    pub struct FastUnixStream {
+        io: Arc<Async<UnixStream>>,
+    }
+
+    impl FastUnixStream {
+        /// Connects to a Unix-domain socket path using non-blocking mode.
+        #[inline(always)]
+        pub fn connect<P: AsRef<Path>>(path: P, reactor: Arc<Reactor>) -> io::Result<Self> {
+            let stream = UnixStream::connect(path)?;
+            Ok(Self {
+                io: Arc::new(Async::new(stream, reactor)?),
+            })
+        }
+
+        /// Attempts to read into the provided buffer. Use in a loop until WouldBlock.
+        #[inline(always)]
+        pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut stream = self.io.get_ref();
+            stream.read(buf)
+        }
+
+        /// Attempts to write buffer to stream. Use in a loop until WouldBlock.
+        #[inline(always)]
+        pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+            let mut stream = self.io.get_ref();
+            stream.write(buf)
+        }
+
+        /// Reads into `buf`, awaiting readability between `WouldBlock` attempts.
+        pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+            self.io.read_with(|mut stream| stream.read(buf)).await
+        }
+
+        /// Writes `buf`, awaiting writability between `WouldBlock` attempts.
+        pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+            self.io.write_with(|mut stream| stream.write(buf)).await
+        }
+
+        /// Splits the stream into independent read/write halves sharing the
+        /// same underlying connection, so one task can own each direction.
+        pub fn split(&self) -> (FastUnixStreamReadHalf, FastUnixStreamWriteHalf) {
+            (
+                FastUnixStreamReadHalf { io: self.io.clone() },
+                FastUnixStreamWriteHalf { io: self.io.clone() },
+            )
+        }
+
+        /// Access raw mio stream.
+        #[inline(always)]
+        pub fn raw(&self) -> &UnixStream {
+            self.io.get_ref()
+        }
+    }
+
+    /// Read half of a [`FastUnixStream`] produced by [`FastUnixStream::split`].
+    pub struct FastUnixStreamReadHalf {
+        io: Arc<Async<UnixStream>>,
+    }
+
+    impl FastUnixStreamReadHalf {
+        /// Resolves once the socket is readable.
+        pub async fn readable(&self) -> io::Result<()> {
+            self.io.readable().await
+        }
+
+        /// Attempts to read into the provided buffer. Use in a loop until WouldBlock.
+        #[inline(always)]
+        pub fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut stream = self.io.get_ref();
+            stream.read(buf)
+        }
+
+        /// Reads into `buf`, awaiting readability between `WouldBlock` attempts.
+        pub async fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+            self.io.read_with(|mut stream| stream.read(buf)).await
+        }
+    }
+
+    /// Write half of a [`FastUnixStream`] produced by [`FastUnixStream::split`].
+    pub struct FastUnixStreamWriteHalf {
+        io: Arc<Async<UnixStream>>,
+    }
+
+    impl FastUnixStreamWriteHalf {
+        /// Resolves once the socket is writable.
+        pub async fn writable(&self) -> io::Result<()> {
+            self.io.writable().await
+        }
+
+        /// Attempts to write buffer to stream. Use in a loop until WouldBlock.
+        #[inline(always)]
+        pub fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+            let mut stream = self.io.get_ref();
+            stream.write(buf)
+        }
+
+        /// Writes `buf`, awaiting writability between `WouldBlock` attempts.
+        pub async fn write(&self, buf: &[u8]) -> io::Result<usize> {
+            self.io.write_with(|mut stream| stream.write(buf)).await
+        }
+    }
+
+    /// Wrapper around a non-blocking Unix-domain listener.
+    pub struct FastUnixListener {
+        io: Async<UnixListener>,
+    }
+
+    impl FastUnixListener {
+        /// Binds a Unix-domain socket path using non-blocking mode.
+        #[inline(always)]
+        pub fn bind<P: AsRef<Path>>(path: P, reactor: Arc<Reactor>) -> io::Result<Self> {
+            let listener = UnixListener::bind(path)?;
+            Ok(Self {
+                io: Async::new(listener, reactor)?,
+            })
+        }
+
+        /// Accepts one pending connection. Use in a loop until WouldBlock.
+        #[inline(always)]
+        pub fn try_accept(&self) -> io::Result<FastUnixStream> {
+            let reactor = self.io.reactor().clone();
+            let (stream, _) = self.io.get_ref().accept()?;
+            Ok(FastUnixStream {
+                io: Arc::new(Async::new(stream, reactor)?),
+            })
+        }
+
+        /// Accepts the next connection, awaiting readability between `WouldBlock` attempts.
+        pub async fn accept(&self) -> io::Result<FastUnixStream> {
+            let reactor = self.io.reactor().clone();
+            let (stream, _) = self.io.read_with(|listener| listener.accept()).await?;
+            Ok(FastUnixStream {
+                io: Arc::new(Async::new(stream, reactor)?),
+            })
+        }
+
+        /// Access raw mio listener.
+        #[inline(always)]
+        pub fn raw(&self) -> &UnixListener {
+            self.io.get_ref()
+        }
     }
 }
+
+#[cfg(unix)]
+pub use unix::{FastUnixListener, FastUnixStream, FastUnixStreamReadHalf, FastUnixStreamWriteHalf};
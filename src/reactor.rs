@@ -3,40 +3,106 @@
 use std::{
     io,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
     task::Waker,
+    time::{Duration, Instant},
 };
 
+use crossbeam::queue::SegQueue;
 use mio::{Token};
 use slab::Slab;
 
+use crate::executor::Shards;
 use crate::poller::Poller;
+use crate::task::Task;
+use crate::timer::{TimerKey, TimerQueue};
 use parking_lot::Mutex;
 
-/// Internal reactor state, maps tokens to wakers.
+/// Reserved token for the internal loop-wake handle (see `Reactor::wake_loop`).
+/// `register_source` hands out slab indices starting at `0` and growing, so a
+/// token this large can never collide with a real registered source.
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+/// Rounds a duration up to the nearest whole millisecond for `mio::Poll`,
+/// whose timeout is millisecond-granular. Rounding down instead would let a
+/// deadline a fraction of a millisecond away report as already due, so the
+/// poll returns before the real deadline and the next iteration recomputes a
+/// sub-millisecond remainder that truncates to `0` — spinning in a near-busy
+/// loop until the deadline actually lands.
+fn ceil_ms(d: Duration) -> u64 {
+    (d.as_nanos() as u64).div_ceil(1_000_000)
+}
+
+/// Per-token readiness state: at most one waker waiting on each direction,
+/// plus a sticky "became ready" flag per direction.
+///
+/// Keeping read and write wakers separate (rather than a single `Waker` per
+/// token) lets one task read a socket while another writes it without the
+/// two clobbering each other's wakeup. The ready flags let a future confirm
+/// *why* it was polled again instead of assuming any re-poll means its own
+/// direction fired — needed when the same waker is shared across multiple
+/// pending sub-futures, e.g. `select!` between `readable()` and a `Timer`.
+#[derive(Default)]
+struct Source {
+    read: Option<Waker>,
+    write: Option<Waker>,
+    read_ready: bool,
+    write_ready: bool,
+}
+
+/// Internal reactor state, maps tokens to per-direction wakers.
 struct ReactorState {
-    wakers: Slab<Waker>,
+    sources: Slab<Source>,
 }
 
 /// Core reactor driving async task wakeups and event polling.
 pub struct Reactor {
     poller: Arc<Poller>,
     state: Mutex<ReactorState>,
+    timers: Mutex<TimerQueue>,
     next_token: AtomicUsize,
+    /// Set once `run_multi` spins up a work-stealing worker pool; while
+    /// present, `spawn_task` routes through it instead of `task_queue`.
+    shards: Mutex<Option<Arc<Shards>>>,
+    /// `true` once any task has been routed onto the single-threaded
+    /// `task_queue`, i.e. before `run_multi` ever started a worker pool.
+    /// `run_multi` asserts this is still `false` — see `spawn_task`.
+    spawned_single_threaded: AtomicBool,
+    /// Single-threaded fallback run queue; only drained by `poll_tasks` when
+    /// `run_multi` hasn't started a worker pool. See `spawn_task`.
+    task_queue: SegQueue<Arc<Task>>,
+    /// Count of tasks spawned but not yet completed, across both the
+    /// `task_queue` and `Shards` paths. `poll_tasks` uses this to tell "the
+    /// queue is momentarily empty" apart from "nothing is left to run",
+    /// since a task awaiting I/O or a timer isn't queued but is still alive.
+    live_tasks: AtomicUsize,
+    /// OS-level handle that unblocks a parked `Poll::poll` independently of
+    /// any registered source or timer. `wake_loop` trips it whenever a task
+    /// is queued on `task_queue`, when the last live task completes, and when
+    /// a new earliest timer is scheduled, so `run`/`run_multi` never block
+    /// forever just because nothing is currently pending.
+    waker: mio::Waker,
 }
 
 impl Reactor {
     /// Creates a new Reactor instance backed by the provided Poller.
-    pub fn new(poller: Arc<Poller>) -> Arc<Self> {
-        Arc::new(Self {
+    pub fn new(poller: Arc<Poller>) -> io::Result<Arc<Self>> {
+        let waker = mio::Waker::new(poller.registry(), WAKE_TOKEN)?;
+        Ok(Arc::new(Self {
             poller,
             state: Mutex::new(ReactorState {
-                wakers: Slab::with_capacity(1024),
+                sources: Slab::with_capacity(1024),
             }),
+            timers: Mutex::new(TimerQueue::new()),
             next_token: AtomicUsize::new(0),
-        })
+            shards: Mutex::new(None),
+            spawned_single_threaded: AtomicBool::new(false),
+            task_queue: SegQueue::new(),
+            live_tasks: AtomicUsize::new(0),
+            waker,
+        }))
     }
 
     /// Returns the global singleton Reactor instance.
@@ -44,27 +110,84 @@ impl Reactor {
         use once_cell::sync::Lazy;
         static INSTANCE: Lazy<Arc<Reactor>> = Lazy::new(|| {
             let poller = Poller::new().expect("Failed to create Poller");
-            Reactor::new(poller)
+            Reactor::new(poller).expect("Failed to create Reactor wake handle")
         });
         INSTANCE.clone()
     }
 
-    /// Registers a waker and returns its unique token.
+    /// Notifies the reactor that a task is ready to run, unblocking a
+    /// `poll_events` call currently parked on `Poll::poll` with no pending
+    /// timer or I/O registration to wake it otherwise.
+    pub(crate) fn wake_loop(&self) {
+        if let Err(e) = self.waker.wake() {
+            eprintln!("Reactor wake_loop error: {:?}", e);
+        }
+    }
+
+    /// Registers a fresh source and returns its unique token.
     ///
-    /// Minimizes locked scope to reduce contention.
-    pub fn register_waker(&self, waker: Waker) -> Token {
+    /// Neither direction has a waker yet; callers arm them via
+    /// `set_reader`/`set_writer` once a task actually awaits readiness.
+    pub fn register_source(&self) -> Token {
         let mut state = self.state.lock();
-        Token(state.wakers.insert(waker))
+        Token(state.sources.insert(Source::default()))
     }
 
-    /// Removes the waker associated with the token.
+    /// Removes the source and any wakers still registered for it.
     pub fn deregister(&self, token: Token) {
         let mut state = self.state.lock();
-        state.wakers.remove(token.0);
+        state.sources.remove(token.0);
+    }
+
+    /// Arms the read-side waker for `token`, replacing any previous one.
+    pub fn set_reader(&self, token: Token, waker: Waker) {
+        let mut state = self.state.lock();
+        if let Some(source) = state.sources.get_mut(token.0) {
+            source.read = Some(waker);
+        }
+    }
+
+    /// Arms the write-side waker for `token`, replacing any previous one.
+    pub fn set_writer(&self, token: Token, waker: Waker) {
+        let mut state = self.state.lock();
+        if let Some(source) = state.sources.get_mut(token.0) {
+            source.write = Some(waker);
+        }
+    }
+
+    /// Returns whether the read side of `token` became ready since the last
+    /// call, clearing the flag so it must be observed again before the next
+    /// `true`.
+    pub fn take_reader_ready(&self, token: Token) -> bool {
+        let mut state = self.state.lock();
+        match state.sources.get_mut(token.0) {
+            Some(source) if source.read_ready => {
+                source.read_ready = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns whether the write side of `token` became ready since the last
+    /// call, clearing the flag so it must be observed again before the next
+    /// `true`.
+    pub fn take_writer_ready(&self, token: Token) -> bool {
+        let mut state = self.state.lock();
+        match state.sources.get_mut(token.0) {
+            Some(source) if source.write_ready => {
+                source.write_ready = false;
+                true
+            }
+            _ => false,
+        }
     }
 
     /// Polls OS events with an optional timeout and wakes the relevant tasks.
     ///
+    /// Only the waker matching the reported direction(s) is woken; it is
+    /// taken out of the slot so it must be re-armed before the next wait.
+    ///
     /// Returns any I/O error encountered during polling.
     pub fn poll_events(&self, timeout_ms: Option<u64>) -> io::Result<()> {
         let events = self.poller.poll(timeout_ms)?;
@@ -73,19 +196,58 @@ impl Reactor {
         let mut state = self.state.lock();
 
         for event in events {
-            if let Some(waker) = state.wakers.get(event.token().0) {
-                waker.wake_by_ref();
+            if let Some(source) = state.sources.get_mut(event.token().0) {
+                if event.is_readable() {
+                    source.read_ready = true;
+                    if let Some(waker) = source.read.take() {
+                        waker.wake();
+                    }
+                }
+                if event.is_writable() {
+                    source.write_ready = true;
+                    if let Some(waker) = source.write.take() {
+                        waker.wake();
+                    }
+                }
             }
         }
 
         Ok(())
     }
 
+    /// Schedules `waker` to fire at `deadline`, returning a key for cancellation.
+    ///
+    /// Wakes the event loop when `deadline` is earlier than every pending
+    /// timer, so a loop already parked on a later (or no) timeout recomputes it.
+    pub fn schedule_timer(&self, deadline: Instant, waker: Waker) -> TimerKey {
+        let mut timers = self.timers.lock();
+        let now = Instant::now();
+        let earliest = timers.next_timeout(now).map_or(true, |t| deadline < now + t);
+        let key = timers.schedule(deadline, waker);
+        drop(timers);
+        if earliest {
+            self.wake_loop();
+        }
+        key
+    }
+
+    /// Cancels a previously scheduled timer.
+    pub fn cancel_timer(&self, key: TimerKey) {
+        self.timers.lock().cancel(key);
+    }
+
+    /// Replaces the waker armed for a previously scheduled timer `key`.
+    pub fn rearm_timer(&self, key: TimerKey, waker: Waker) {
+        self.timers.lock().rearm(key, waker);
+    }
+
     /// Spawns a new async task onto this Reactor.
     ///
-    /// Relies on the Task abstraction for task scheduling.
+    /// Relies on the Task abstraction for task scheduling. Takes `&Arc<Self>`
+    /// rather than `&self` because `Task::spawn` needs to hold an owned
+    /// `Arc<Reactor>` to schedule the task's future wakeups against.
     pub fn spawn(
-        &self,
+        self: &Arc<Self>,
         future: std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send + 'static>>,
     ) {
         crate::task::Task::spawn(future, self.clone());
@@ -93,23 +255,211 @@ impl Reactor {
 
     /// Runs the event loop until all tasks complete.
     ///
+    /// Blocks for exactly as long as the nearest timer deadline allows,
+    /// rather than a fixed interval, so idle periods don't wake the thread
+    /// needlessly and long delays aren't truncated.
+    ///
     /// This method blocks the current thread.
     pub fn run(&self) {
         loop {
-            // Poll OS events and wake tasks with a short timeout
-            if let Err(e) = self.poll_events(Some(100)) {
+            let now = Instant::now();
+            let timeout_ms = self.timers.lock().next_timeout(now).map(ceil_ms);
+
+            if let Err(e) = self.poll_events(timeout_ms) {
                 eprintln!("Reactor polling error: {:?}", e);
             }
 
+            self.timers.lock().fire_due(Instant::now());
+
             // Drive all ready tasks; exit if none remain
-            if !crate::task::Task::poll_tasks() {
+            if !self.poll_tasks() {
                 break;
             }
         }
     }
 
+    /// Runs the event loop in fixed time slices of at most `max_throttle`,
+    /// coalescing I/O and timer processing instead of draining the task
+    /// queue and OS events as fast as possible.
+    ///
+    /// This deliberately batches wakeups: on a slice that finishes early the
+    /// loop parks until the slice boundary, trading up to `max_throttle` of
+    /// added latency for fewer syscalls and context switches under
+    /// high-message-rate workloads (many small UDP/TCP packets).
+    pub fn run_throttled(&self, max_throttle: std::time::Duration) {
+        loop {
+            let slice_start = Instant::now();
+            let slice_end = slice_start + max_throttle;
+
+            let poll_timeout_ms = ceil_ms(
+                self.timers
+                    .lock()
+                    .next_timeout(slice_start)
+                    .unwrap_or(max_throttle)
+                    .min(max_throttle),
+            );
+
+            if let Err(e) = self.poll_events(Some(poll_timeout_ms)) {
+                eprintln!("Reactor polling error: {:?}", e);
+            }
+
+            // Only fire timers that have actually elapsed. Using `slice_end`
+            // here would pull deadlines that haven't arrived yet forward,
+            // desyncing them from the `TimerQueue` entries `Timer`/`Interval`
+            // still think are pending and hanging those futures permanently.
+            // The sleep-until-`slice_end` below already gives the intended
+            // batching effect for anything that becomes ready early.
+            self.timers.lock().fire_due(Instant::now());
+
+            if !self.poll_tasks() {
+                break;
+            }
+
+            let now = Instant::now();
+            if now < slice_end {
+                std::thread::sleep(slice_end - now);
+            }
+        }
+    }
+
+    /// Switches task scheduling over to a multi-threaded work-stealing pool
+    /// and drives I/O polling and timers on the calling thread.
+    ///
+    /// Spawns `num_workers` OS threads, each draining its own local shard,
+    /// then the global queue, then stealing from a sibling shard before
+    /// parking. This replaces the single `task_queue` as the scheduling
+    /// path for the lifetime of the reactor.
+    ///
+    /// Like `run`/`run_throttled`, returns once every spawned task has
+    /// completed — `live_tasks` is incremented by `Task::spawn` and
+    /// decremented by `Task::poll` once the task's future resolves,
+    /// regardless of which queue a task lands on, so the same
+    /// `poll_tasks` check works here even though this loop never drains
+    /// `task_queue` itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a task has already been spawned on this reactor via the
+    /// single-threaded `task_queue` path. `run_multi` must be the first
+    /// thing started on a fresh reactor: anything already queued before the
+    /// worker pool exists would otherwise be stranded forever, since nothing
+    /// ever drains `task_queue` once scheduling switches over to `Shards`.
+    pub fn run_multi(&self, num_workers: usize) {
+        assert!(
+            !self.spawned_single_threaded.load(Ordering::Acquire),
+            "run_multi must be called before any task is spawned on this reactor"
+        );
+        *self.shards.lock() = Some(Shards::spawn_workers(num_workers));
+
+        loop {
+            let now = Instant::now();
+            let timeout_ms = self.timers.lock().next_timeout(now).map(ceil_ms);
+
+            if let Err(e) = self.poll_events(timeout_ms) {
+                eprintln!("Reactor polling error: {:?}", e);
+            }
+
+            self.timers.lock().fire_due(Instant::now());
+
+            if !self.poll_tasks() {
+                break;
+            }
+        }
+    }
+
+    /// Returns the active worker-pool handle, if `run_multi` has been started.
+    pub(crate) fn shards(&self) -> Option<Arc<Shards>> {
+        self.shards.lock().clone()
+    }
+
+    /// Records that a task has been routed onto the single-threaded
+    /// `task_queue` path, so `run_multi` can refuse to start once that's
+    /// happened — see its doc comment.
+    pub(crate) fn mark_spawned_single_threaded(&self) {
+        self.spawned_single_threaded.store(true, Ordering::Release);
+    }
+
+    /// Access to the single-threaded fallback run queue; the field itself
+    /// is private to this module, so `Task`'s `impl Reactor` block in
+    /// `task.rs` has to go through this accessor, same as `shards()`.
+    pub(crate) fn task_queue(&self) -> &SegQueue<Arc<Task>> {
+        &self.task_queue
+    }
+
+    /// Records a newly spawned task, regardless of which queue it lands on.
+    pub(crate) fn inc_live_tasks(&self) {
+        self.live_tasks.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Records that a task's future has resolved. When the last live task
+    /// completes, wakes the event loop so a `run`/`run_multi` loop parked in
+    /// `poll_events` notices that nothing is left to run.
+    pub(crate) fn dec_live_tasks(&self) {
+        if self.live_tasks.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.wake_loop();
+        }
+    }
+
+    /// Drains the single-threaded `task_queue`, polling every task once.
+    ///
+    /// Returns whether any spawned task is still live. A task that's merely
+    /// waiting on I/O or a timer isn't sitting in `task_queue` between polls,
+    /// so an empty queue alone doesn't mean the program is done — `run`/
+    /// `run_throttled` use this instead of an emptiness check to decide when
+    /// to stop looping.
+    pub fn poll_tasks(&self) -> bool {
+        while let Some(task) = self.task_queue.pop() {
+            task.poll();
+        }
+        self.live_tasks.load(Ordering::Acquire) > 0
+    }
+
     /// Access the underlying poller.
     pub fn poller(&self) -> Arc<Poller> {
         self.poller.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ceil_ms_rounds_a_whole_millisecond_to_itself() {
+        assert_eq!(ceil_ms(Duration::from_millis(5)), 5);
+    }
+
+    #[test]
+    fn ceil_ms_rounds_a_sub_millisecond_remainder_up() {
+        assert_eq!(ceil_ms(Duration::from_micros(5001)), 6);
+    }
+
+    #[test]
+    fn ceil_ms_rounds_a_zero_duration_to_zero() {
+        assert_eq!(ceil_ms(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn ceil_ms_never_rounds_down() {
+        assert_eq!(ceil_ms(Duration::from_nanos(1)), 1);
+    }
+
+    #[test]
+    fn run_multi_returns_once_every_spawned_task_completes() {
+        let reactor = Reactor::new(Poller::new().unwrap()).unwrap();
+        let runner = reactor.clone();
+        let handle = std::thread::spawn(move || runner.run_multi(2));
+
+        // Wait for the worker pool to be installed before spawning, so the
+        // task is guaranteed to route through `Shards` rather than racing
+        // `run_multi`'s single-threaded-path assertion.
+        while reactor.shards().is_none() {
+            std::thread::yield_now();
+        }
+        reactor.spawn(Box::pin(async {}));
+
+        handle
+            .join()
+            .expect("run_multi should return once the spawned task completes, not loop forever");
+    }
+}
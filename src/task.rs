@@ -12,7 +12,6 @@ use std::{
     task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
-use crossbeam::queue::SegQueue;
 use parking_lot::Mutex;
 
 use crate::reactor::Reactor;
@@ -50,6 +49,19 @@ impl Task {
         }
     }
 
+    /// Creates a task and schedules it for its first poll, returning a
+    /// handle to it.
+    pub fn spawn(
+        future: Pin<Box<dyn Future<Output = ()> + Send>>,
+        reactor: Arc<Reactor>,
+    ) -> Arc<Task> {
+        reactor.inc_live_tasks();
+        let task = Arc::new(Self::new(future, reactor.clone()));
+        task.inner.is_scheduled.store(true, Ordering::Release);
+        reactor.spawn_task(task.clone());
+        task
+    }
+
     /// Polls the task's future once.
     pub fn poll(self: Arc<Self>) {
         // If not scheduled, no need to poll.
@@ -77,6 +89,7 @@ impl Task {
                 if let Some(token) = self.inner.token {
                     self.inner.reactor.deregister(token);
                 }
+                self.inner.reactor.dec_live_tasks();
             }
         }
     }
@@ -121,7 +134,10 @@ impl Task {
     }
 
     /// Schedule this task for polling if not already scheduled.
-    fn schedule(&self) {
+    ///
+    /// Takes `&Arc<Self>` rather than `&self` so `self.clone()` hands
+    /// `spawn_task` the `Arc<Task>` it expects, not a bare `Task`.
+    fn schedule(self: &Arc<Self>) {
         // Only enqueue if not already scheduled.
         if !self.inner.is_scheduled.swap(true, Ordering::AcqRel) {
             self.inner.reactor.spawn_task(self.clone());
@@ -130,11 +146,18 @@ impl Task {
 }
 
 impl Reactor {
-    /// Spawn a new task into the reactor's task queue.
+    /// Spawn a new task for execution.
     ///
-    /// Assumes a lock-free queue `task_queue` and a method `wake_loop` to notify the event loop.
+    /// Routes onto the work-stealing worker pool if `run_multi` started one;
+    /// otherwise falls back to the single-threaded `task_queue`, draining
+    /// via `poll_tasks` and waking the event loop so it notices.
     pub fn spawn_task(&self, task: Arc<Task>) {
-        self.task_queue.push(task);
-        self.wake_loop();
+        if let Some(shards) = self.shards() {
+            shards.spawn_task(task);
+        } else {
+            self.mark_spawned_single_threaded();
+            self.task_queue().push(task);
+            self.wake_loop();
+        }
     }
 }
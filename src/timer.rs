@@ -1,89 +1,286 @@
-//! High-performance, lock-free-ish TimerWheel with cancellation and OOM-safety for fastloo.
+//! Ordered timer queue for fastloop — drives the reactor's poll timeout
+//! instead of a fixed-span wheel, so delays of arbitrary length fire exactly
+//! on time and idle loops sleep until the next deadline rather than spinning.
 
 use std::{
-    sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
-        Arc,
-    },
-    task::Waker,
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    task::{Context, Poll, Waker},
     time::{Duration, Instant},
 };
-use crossbeam::queue::SegQueue;
 
-const WHEEL_SIZE: usize = 256;
-const SLOT_DURATION_MS: u64 = 10;
+use futures_core::stream::{FusedStream, Stream};
+
+use crate::reactor::Reactor;
+
 pub type TimerId = u64;
 
-#[derive(Debug)]
-pub struct TimerEntry {
-    id: TimerId,
-    expiration_slot: usize,
-    waker: Arc<Waker>,
-    canceled: AtomicBool,
+/// Handle returned by `TimerQueue::schedule`, needed to cancel the timer.
+pub type TimerKey = (Instant, TimerId);
+
+/// Deadline-ordered set of pending timers, guarded by the reactor.
+pub struct TimerQueue {
+    deadlines: BTreeMap<TimerKey, Waker>,
+    next_id: AtomicU64,
 }
 
-impl TimerEntry {
-    pub fn cancel(&self) {
-        self.canceled.store(true, Ordering::Release);
+impl TimerQueue {
+    pub fn new() -> Self {
+        Self {
+            deadlines: BTreeMap::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Schedules `waker` to fire at `deadline`, returning a key for cancellation.
+    pub fn schedule(&mut self, deadline: Instant, waker: Waker) -> TimerKey {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let key = (deadline, id);
+        self.deadlines.insert(key, waker);
+        key
+    }
+
+    /// Cancels a previously scheduled timer; a no-op if it already fired.
+    pub fn cancel(&mut self, key: TimerKey) {
+        self.deadlines.remove(&key);
+    }
+
+    /// Replaces the waker armed for an already-scheduled `key` in place,
+    /// without disturbing its position in the deadline order. A no-op if the
+    /// timer already fired and was removed.
+    pub fn rearm(&mut self, key: TimerKey, waker: Waker) {
+        if let Some(slot) = self.deadlines.get_mut(&key) {
+            *slot = waker;
+        }
+    }
+
+    /// Wakes and removes every timer due at or before `now`.
+    pub fn fire_due(&mut self, now: Instant) {
+        let still_pending = self.deadlines.split_off(&(now + Duration::from_nanos(1), 0));
+        let due = std::mem::replace(&mut self.deadlines, still_pending);
+        for (_, waker) in due {
+            waker.wake();
+        }
+    }
+
+    /// Duration until the next deadline, or `None` if no timers are pending.
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.deadlines
+            .keys()
+            .next()
+            .map(|(deadline, _)| deadline.saturating_duration_since(now))
     }
 }
 
-pub struct TimerWheel {
-    current_slot: AtomicU64,
-    slots: Vec<SegQueue<Arc<TimerEntry>>>,
-    next_id: AtomicU64,
+impl Default for TimerQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A future that resolves to the current `Instant` once a deadline passes.
+pub struct Timer {
+    reactor: Arc<Reactor>,
+    deadline: Instant,
+    key: Option<TimerKey>,
 }
 
-impl TimerWheel {
-    pub fn new() -> Arc<Self> {
-        let mut slots = Vec::with_capacity(WHEEL_SIZE);
-        for _ in 0..WHEEL_SIZE {
-            slots.push(SegQueue::new());
+impl Timer {
+    /// Completes after `duration` has elapsed.
+    pub fn after(duration: Duration) -> Self {
+        Self::at(Instant::now() + duration)
+    }
+
+    /// Completes once `deadline` is reached.
+    pub fn at(deadline: Instant) -> Self {
+        Self {
+            reactor: Reactor::global(),
+            deadline,
+            key: None,
         }
+    }
+}
 
-        Arc::new(Self {
-            current_slot: AtomicU64::new(0),
-            slots,
-            next_id: AtomicU64::new(1),
-        })
+impl Future for Timer {
+    type Output = Instant;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let now = Instant::now();
+        if now >= this.deadline {
+            if let Some(key) = this.key.take() {
+                this.reactor.cancel_timer(key);
+            }
+            return Poll::Ready(now);
+        }
+        // Re-arm the waker on every poll rather than only the first time,
+        // mirroring `Readable`/`Writable` in async_io.rs: harmless today
+        // since every `Waker` this executor hands out for a task is
+        // interchangeable, but it keeps `Timer` from silently going stale if
+        // that ever changes.
+        match this.key {
+            Some(key) => this.reactor.rearm_timer(key, cx.waker().clone()),
+            None => this.key = Some(this.reactor.schedule_timer(this.deadline, cx.waker().clone())),
+        }
+        Poll::Pending
     }
+}
 
-    /// Schedule a timer to fire after `delay`. Returns TimerId and Arc handle to cancel if needed.
-    pub fn schedule(&self, delay: Duration, waker: Waker) -> (TimerId, Arc<TimerEntry>) {
-        let ticks = (delay.as_millis() / SLOT_DURATION_MS as u128) as usize;
-        let current_slot = self.current_slot.load(Ordering::Acquire) as usize;
-        let expiration_slot = (current_slot + ticks) % WHEEL_SIZE;
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.reactor.cancel_timer(key);
+        }
+    }
+}
 
-        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+/// A stream that ticks every `period`, re-arming itself after each tick.
+pub struct Interval {
+    reactor: Arc<Reactor>,
+    period: Duration,
+    next: Instant,
+    key: Option<TimerKey>,
+}
 
-        let entry = Arc::new(TimerEntry {
-            id,
-            expiration_slot,
-            waker: Arc::new(waker),
-            canceled: AtomicBool::new(false),
-        });
-
-        self.slots[expiration_slot].push(entry.clone());
-        (id, entry)
-    }
-
-    /// Advance wheel by one slot and fire all timers in the current slot.
-    pub fn tick(&self) {
-        let current_slot = self
-            .current_slot
-            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |val| {
-                Some((val + 1) % WHEEL_SIZE as u64)
-            })
-            .unwrap_or(0) as usize;
-
-        let slot = &self.slots[current_slot];
-
-        // Fire all timers in this slot
-        while let Some(timer) = slot.pop() {
-            if !timer.canceled.load(Ordering::Acquire) {
-                // Wake only if not canceled
-                timer.waker.wake_by_ref();
+impl Interval {
+    /// Creates an interval that first fires one `period` from now.
+    pub fn new(period: Duration) -> Self {
+        Self {
+            reactor: Reactor::global(),
+            period,
+            next: Instant::now() + period,
+            key: None,
+        }
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let now = Instant::now();
+        if now >= this.next {
+            if let Some(key) = this.key.take() {
+                this.reactor.cancel_timer(key);
             }
+            this.next += this.period;
+            return Poll::Ready(Some(now));
+        }
+        // See the comment in `Timer::poll`: re-arm on every poll instead of
+        // only the first, to match `Readable`/`Writable`'s pattern.
+        match this.key {
+            Some(key) => this.reactor.rearm_timer(key, cx.waker().clone()),
+            None => this.key = Some(this.reactor.schedule_timer(this.next, cx.waker().clone())),
+        }
+        Poll::Pending
+    }
+}
+
+impl FusedStream for Interval {
+    /// An `Interval` never naturally ends; it ticks forever until dropped.
+    fn is_terminated(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for Interval {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.reactor.cancel_timer(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::atomic::AtomicBool, task::Wake};
+
+    struct FlagWaker(AtomicBool);
+
+    impl Wake for FlagWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
         }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn flag_waker() -> (Arc<FlagWaker>, Waker) {
+        let flag = Arc::new(FlagWaker(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        (flag, waker)
+    }
+
+    #[test]
+    fn next_timeout_is_none_when_queue_is_empty() {
+        let queue = TimerQueue::new();
+        assert_eq!(queue.next_timeout(Instant::now()), None);
+    }
+
+    #[test]
+    fn fire_due_fires_a_deadline_exactly_at_now_but_not_one_later() {
+        let mut queue = TimerQueue::new();
+        let now = Instant::now();
+        let (due_flag, due_waker) = flag_waker();
+        let (pending_flag, pending_waker) = flag_waker();
+        queue.schedule(now, due_waker);
+        queue.schedule(now + Duration::from_millis(50), pending_waker);
+
+        queue.fire_due(now);
+
+        assert!(due_flag.0.load(Ordering::SeqCst), "a deadline exactly at `now` is due");
+        assert!(!pending_flag.0.load(Ordering::SeqCst), "a later deadline must not fire yet");
+        assert_eq!(queue.next_timeout(now), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn fire_due_is_idempotent_once_a_timer_has_fired() {
+        let mut queue = TimerQueue::new();
+        let now = Instant::now();
+        let (flag, waker) = flag_waker();
+        queue.schedule(now, waker);
+
+        queue.fire_due(now);
+        flag.0.store(false, Ordering::SeqCst);
+        queue.fire_due(now);
+
+        assert!(!flag.0.load(Ordering::SeqCst), "an already-fired timer must not fire again");
+    }
+
+    #[test]
+    fn rearm_replaces_the_waker_without_changing_the_deadline() {
+        let mut queue = TimerQueue::new();
+        let now = Instant::now();
+        let (stale_flag, stale_waker) = flag_waker();
+        let (fresh_flag, fresh_waker) = flag_waker();
+        let key = queue.schedule(now, stale_waker);
+
+        queue.rearm(key, fresh_waker);
+        queue.fire_due(now);
+
+        assert!(!stale_flag.0.load(Ordering::SeqCst), "the waker captured before rearm must not fire");
+        assert!(fresh_flag.0.load(Ordering::SeqCst), "the rearmed waker should fire in its place");
+    }
+
+    #[test]
+    fn rearm_on_an_already_fired_key_is_a_noop() {
+        let mut queue = TimerQueue::new();
+        let now = Instant::now();
+        let (flag, waker) = flag_waker();
+        let key = queue.schedule(now, waker);
+        queue.fire_due(now);
+        flag.0.store(false, Ordering::SeqCst);
+
+        let (late_flag, late_waker) = flag_waker();
+        queue.rearm(key, late_waker);
+
+        assert!(!flag.0.load(Ordering::SeqCst));
+        assert!(!late_flag.0.load(Ordering::SeqCst), "rearming a key that already fired must not resurrect it");
     }
 }